@@ -21,13 +21,46 @@ pub struct Instruction {
     pub handler: fn(cpu: &mut Cpu, op_code: &Opcode) -> ExecutionType,
 }
 
-pub fn get_instruction_by_op_code(op_code: &Opcode) -> Option<&Instruction> {
+// Private to this module: execute() is the only supported dispatch entry point, since
+// it's also what drives Mmu::step/dma_tick/try_speed_switch off the
+// instruction's clock cost. Looking up and invoking `handler` directly would
+// silently skip all of that.
+fn get_instruction_by_op_code(op_code: &Opcode) -> Option<&Instruction> {
     match op_code {
         Opcode::Regular(value) => instructions::get_instruction(&value),
         Opcode::CB(value) => cb_instructions::get_instruction(&value),
     }
 }
 
+const STOP_OP_CODE: u8 = 0x10;
+const T_CYCLES_PER_M_CYCLE: u8 = 4;
+
+// Looks up and runs the instruction for `op_code`, then advances everything
+// clocked off the CPU's step count. The main fetch-decode-execute loop
+// should call this instead of dispatching `Instruction::handler` itself.
+pub fn execute(cpu: &mut Cpu, op_code: &Opcode) -> ExecutionType {
+    let instruction = get_instruction_by_op_code(op_code).expect("unimplemented opcode");
+    let result = (instruction.handler)(cpu, op_code);
+
+    cpu.mmu.step(instruction.clock_cycles);
+
+    for _ in 0..instruction.clock_cycles / T_CYCLES_PER_M_CYCLE {
+        cpu.mmu.dma_tick();
+    }
+
+    // STOP only performs the CGB speed switch when KEY1's armed bit is set;
+    // try_speed_switch is a no-op otherwise.
+    if is_stop(op_code) {
+        cpu.mmu.try_speed_switch();
+    }
+
+    result
+}
+
+fn is_stop(op_code: &Opcode) -> bool {
+    matches!(op_code, Opcode::Regular(STOP_OP_CODE))
+}
+
 fn read_hl_addr(cpu: &Cpu) -> u8 {
     cpu.mmu
         .read(bytes_to_word(cpu.registers.h, cpu.registers.l))
@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+const OAM_TRANSFER_LENGTH: u16 = 160;
+
+// OAM DMA is latched by a write to 0xFF46 and then copies one byte per
+// machine cycle, rather than completing instantaneously.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Dma {
+    source_high: u8,
+    index: u16,
+    active: bool,
+}
+
+impl Dma {
+    pub fn new() -> Dma {
+        Dma {
+            source_high: 0,
+            index: 0,
+            active: false,
+        }
+    }
+
+    pub fn start(&mut self, source_high: u8) {
+        self.source_high = source_high;
+        self.index = 0;
+        self.active = true;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    // Advances the transfer by one byte, returning the (source, OAM offset)
+    // to copy if a transfer is in progress.
+    pub fn tick(&mut self) -> Option<(u16, u16)> {
+        if !self.active {
+            return None;
+        }
+
+        let source = (self.source_high as u16) << 8 | self.index;
+        let offset = self.index;
+
+        self.index += 1;
+        if self.index >= OAM_TRANSFER_LENGTH {
+            self.active = false;
+        }
+
+        Some((source, offset))
+    }
+}
@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+
+const DIRECTION_SELECT_BIT: u8 = 0x10;
+const ACTION_SELECT_BIT: u8 = 0x20;
+
+#[derive(Clone, Copy)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+// The joypad matrix register (0xFF00). Writing selects the direction or
+// action button group (active low); reading returns that group's four
+// button states in the low nibble, also active low.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Joypad {
+    select: u8,
+    direction_state: u8,
+    action_state: u8,
+}
+
+impl Joypad {
+    pub fn new() -> Joypad {
+        Joypad {
+            select: 0x30,
+            direction_state: 0,
+            action_state: 0,
+        }
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.select = value & 0x30;
+    }
+
+    pub fn read(&self) -> u8 {
+        let direction_selected = self.select & DIRECTION_SELECT_BIT == 0;
+        let action_selected = self.select & ACTION_SELECT_BIT == 0;
+
+        let pressed = match (direction_selected, action_selected) {
+            (true, true) => self.direction_state & self.action_state,
+            (true, false) => self.direction_state,
+            (false, true) => self.action_state,
+            (false, false) => 0,
+        };
+
+        0xC0 | self.select | (!pressed & 0x0F)
+    }
+
+    // Updates a button's pressed state, returning true on a high-to-low
+    // transition (the edge that raises the joypad interrupt).
+    pub fn set_button(&mut self, button: Button, pressed: bool) -> bool {
+        let (state, bit) = self.state_and_bit(button);
+        let was_pressed = *state & bit != 0;
+
+        if pressed {
+            *state |= bit;
+        } else {
+            *state &= !bit;
+        }
+
+        !was_pressed && pressed
+    }
+
+    fn state_and_bit(&mut self, button: Button) -> (&mut u8, u8) {
+        match button {
+            Button::Right => (&mut self.direction_state, 0x01),
+            Button::Left => (&mut self.direction_state, 0x02),
+            Button::Up => (&mut self.direction_state, 0x04),
+            Button::Down => (&mut self.direction_state, 0x08),
+            Button::A => (&mut self.action_state, 0x01),
+            Button::B => (&mut self.action_state, 0x02),
+            Button::Select => (&mut self.action_state, 0x04),
+            Button::Start => (&mut self.action_state, 0x08),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_with_neither_group_selected_reports_nothing_pressed() {
+        let mut joypad = Joypad::new();
+        joypad.write(0x30);
+        joypad.set_button(Button::A, true);
+
+        assert_eq!(joypad.read(), 0xFF);
+    }
+
+    #[test]
+    fn read_with_only_direction_group_selected_reports_direction_state() {
+        let mut joypad = Joypad::new();
+        joypad.set_button(Button::Right, true);
+        joypad.write(0x20); // select direction group (bit 4 low)
+
+        assert_eq!(joypad.read(), 0xE0 | 0x0E);
+    }
+
+    #[test]
+    fn read_with_only_action_group_selected_reports_action_state() {
+        let mut joypad = Joypad::new();
+        joypad.set_button(Button::A, true);
+        joypad.write(0x10); // select action group (bit 5 low)
+
+        assert_eq!(joypad.read(), 0xD0 | 0x0E);
+    }
+
+    #[test]
+    fn read_with_both_groups_selected_ands_the_two_nibbles() {
+        let mut joypad = Joypad::new();
+        joypad.set_button(Button::Right, true); // direction bit 0x01
+        joypad.set_button(Button::A, true); // action bit 0x01
+        joypad.write(0x00); // select both groups
+
+        // Only the button held in both nibbles (bit 0x01) should read pressed.
+        assert_eq!(joypad.read(), 0xC0 | 0x0E);
+
+        joypad.set_button(Button::Up, true); // direction bit 0x04, no action match
+        assert_eq!(joypad.read(), 0xC0 | 0x0E);
+    }
+
+    #[test]
+    fn set_button_reports_a_press_only_on_the_high_to_low_transition() {
+        let mut joypad = Joypad::new();
+
+        assert!(joypad.set_button(Button::Start, true));
+        assert!(!joypad.set_button(Button::Start, true));
+        assert!(!joypad.set_button(Button::Start, false));
+        assert!(joypad.set_button(Button::Start, true));
+    }
+}
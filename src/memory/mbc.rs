@@ -0,0 +1,199 @@
+use serde::{Deserialize, Serialize};
+
+const RAM_ENABLE_ADDRESS_END: u16 = 0x1FFF;
+const ROM_BANK_LOW_ADDRESS_END: u16 = 0x3FFF;
+const MBC5_ROM_BANK_LOW_ADDRESS_END: u16 = 0x2FFF;
+const RAM_BANK_ADDRESS_END: u16 = 0x5FFF;
+const MODE_ADDRESS_END: u16 = 0x7FFF;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MbcType {
+    None,
+    Mbc1,
+    Mbc3,
+    Mbc5,
+}
+
+impl MbcType {
+    pub fn from_header_byte(byte: u8) -> MbcType {
+        match byte {
+            0x01..=0x03 => MbcType::Mbc1,
+            0x0F..=0x13 => MbcType::Mbc3,
+            0x19..=0x1E => MbcType::Mbc5,
+            _ => MbcType::None,
+        }
+    }
+}
+
+// Cartridge types (header byte 0x0147) whose external RAM is battery-backed.
+pub fn has_battery(header_byte: u8) -> bool {
+    matches!(
+        header_byte,
+        0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFF
+    )
+}
+
+// Number of 8 KiB external RAM banks declared by the header's RAM size byte
+// (0x0149).
+pub fn ram_bank_count(header_byte: u8) -> usize {
+    match header_byte {
+        0x02 => 1,
+        0x03 => 4,
+        0x04 => 16,
+        0x05 => 8,
+        _ => 0,
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Mbc {
+    mbc_type: MbcType,
+    rom_bank: u16,
+    ram_bank: u8,
+    ram_enabled: bool,
+    // MBC1 only: 0 = ROM banking mode, 1 = RAM banking mode
+    banking_mode: u8,
+}
+
+impl Mbc {
+    pub fn new(mbc_type: MbcType) -> Mbc {
+        Mbc {
+            mbc_type,
+            rom_bank: 1,
+            ram_bank: 0,
+            ram_enabled: false,
+            banking_mode: 0,
+        }
+    }
+
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        if self.mbc_type == MbcType::None {
+            return;
+        }
+
+        match address {
+            0..=RAM_ENABLE_ADDRESS_END => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=MBC5_ROM_BANK_LOW_ADDRESS_END if self.mbc_type == MbcType::Mbc5 => {
+                self.rom_bank = (self.rom_bank & 0x100) | value as u16
+            }
+            0x3000..=ROM_BANK_LOW_ADDRESS_END if self.mbc_type == MbcType::Mbc5 => {
+                self.rom_bank = (self.rom_bank & 0xFF) | (((value & 0x01) as u16) << 8)
+            }
+            0x2000..=ROM_BANK_LOW_ADDRESS_END => self.set_rom_bank_low_bits(value),
+            0x4000..=RAM_BANK_ADDRESS_END => self.ram_bank = self.ram_bank_bits(value),
+            0x6000..=MODE_ADDRESS_END => self.banking_mode = value & 0x01,
+            _ => {}
+        }
+    }
+
+    fn set_rom_bank_low_bits(&mut self, value: u8) {
+        let bank = match self.mbc_type {
+            MbcType::Mbc1 => (value & 0x1F) as u16,
+            MbcType::Mbc3 => (value & 0x7F) as u16,
+            MbcType::Mbc5 | MbcType::None => unreachable!(),
+        };
+        self.rom_bank = if bank == 0 { 1 } else { bank };
+    }
+
+    fn ram_bank_bits(&self, value: u8) -> u8 {
+        match self.mbc_type {
+            MbcType::Mbc5 => value & 0x0F,
+            _ => value & 0x03,
+        }
+    }
+
+    // Effective ROM bank mapped into the 0x4000-0x7FFF window.
+    pub fn rom_bank(&self) -> u16 {
+        match self.mbc_type {
+            // In ROM banking mode the MBC1 RAM-bank bits act as the upper
+            // two bits of the ROM bank number instead.
+            MbcType::Mbc1 if self.banking_mode == 0 => {
+                self.rom_bank | ((self.ram_bank as u16) << 5)
+            }
+            _ => self.rom_bank,
+        }
+    }
+
+    pub fn ram_bank(&self) -> u8 {
+        match self.mbc_type {
+            MbcType::Mbc1 if self.banking_mode == 0 => 0,
+            _ => self.ram_bank,
+        }
+    }
+
+    pub fn ram_enabled(&self) -> bool {
+        self.mbc_type == MbcType::None || self.ram_enabled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ram_bank_count_maps_header_byte_to_bank_count() {
+        assert_eq!(ram_bank_count(0x00), 0);
+        assert_eq!(ram_bank_count(0x02), 1);
+        assert_eq!(ram_bank_count(0x03), 4);
+        assert_eq!(ram_bank_count(0x04), 16);
+        assert_eq!(ram_bank_count(0x05), 8);
+    }
+
+    #[test]
+    fn mbc1_rom_bank_zero_aliases_to_one() {
+        let mut mbc = Mbc::new(MbcType::Mbc1);
+        mbc.write_register(0x2000, 0x00);
+        assert_eq!(mbc.rom_bank(), 1);
+    }
+
+    #[test]
+    fn mbc1_rom_banking_mode_folds_ram_bank_bits_into_rom_bank() {
+        let mut mbc = Mbc::new(MbcType::Mbc1);
+        mbc.write_register(0x2000, 0x05); // low 5 bits of the ROM bank
+        mbc.write_register(0x4000, 0x03); // upper bits, ROM banking mode by default
+        assert_eq!(mbc.rom_bank(), 0x05 | (0x03 << 5));
+        assert_eq!(mbc.ram_bank(), 0);
+    }
+
+    #[test]
+    fn mbc1_ram_banking_mode_keeps_rom_and_ram_bank_separate() {
+        let mut mbc = Mbc::new(MbcType::Mbc1);
+        mbc.write_register(0x6000, 0x01); // switch to RAM banking mode
+        mbc.write_register(0x2000, 0x05);
+        mbc.write_register(0x4000, 0x03);
+        assert_eq!(mbc.rom_bank(), 0x05);
+        assert_eq!(mbc.ram_bank(), 0x03);
+    }
+
+    #[test]
+    fn mbc5_rom_bank_is_a_full_9_bits_and_never_aliased() {
+        let mut mbc = Mbc::new(MbcType::Mbc5);
+        mbc.write_register(0x2000, 0x00); // low 8 bits, unlike MBC1 this may be 0
+        mbc.write_register(0x3000, 0x01); // 9th bit
+        assert_eq!(mbc.rom_bank(), 0x100);
+    }
+
+    #[test]
+    fn ram_enable_requires_the_0a_latch_value() {
+        let mut mbc = Mbc::new(MbcType::Mbc1);
+        assert!(!mbc.ram_enabled());
+
+        mbc.write_register(0x0000, 0x0A);
+        assert!(mbc.ram_enabled());
+
+        mbc.write_register(0x0000, 0x00);
+        assert!(!mbc.ram_enabled());
+    }
+
+    #[test]
+    fn mbc_type_none_is_always_ram_enabled() {
+        assert!(Mbc::new(MbcType::None).ram_enabled());
+    }
+
+    #[test]
+    fn mbc_type_none_ignores_register_writes() {
+        let mut mbc = Mbc::new(MbcType::None);
+        mbc.write_register(0x2000, 0x05);
+        assert_eq!(mbc.rom_bank(), 1);
+    }
+}
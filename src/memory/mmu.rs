@@ -1,37 +1,91 @@
+use crate::memory::dma::Dma;
+use crate::memory::joypad::{Button, Joypad};
+use crate::memory::mbc::{has_battery, ram_bank_count, Mbc, MbcType};
+use crate::memory::save::BatterySave;
+use crate::memory::timer::{Timer, DIV_ADDRESS, TAC_ADDRESS};
 use crate::util::binary;
 use crate::Cartridge;
 use crate::Gpu;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
 const EXT_RAM_SIZE: usize = 8192;
-const W_RAM_SIZE: usize = 8192;
+const EXT_RAM_BANK_COUNT: usize = 16;
+const CARTRIDGE_TYPE_ADDRESS: u16 = 0x0147;
+const RAM_SIZE_ADDRESS: u16 = 0x0149;
+const CGB_FLAG_ADDRESS: u16 = 0x0143;
+const W_RAM_BANK_SIZE: usize = 4096;
+const W_RAM_BANK_COUNT: usize = 8;
 const ECHO_RAM_SIZE: usize = 7679;
 const H_RAM_SIZE: usize = 127;
 const OAM_SIZE: usize = 159;
 const IO_SIZE: usize = 127;
 
 const USER_PROGRAM_AREA_ADDRESS: u16 = 0x100;
+const ROM_BANK_0_END: u16 = 0x3FFF;
+const ROM_BANK_N_ADDRESS: u16 = 0x4000;
 const VRAM_ADDRESS: u16 = 0x8000;
 const EXT_RAM_ADDRESS: u16 = 0xA000;
 const ECHO_RAM_ADDRESS: u16 = 0xE000;
 const W_RAM_ADDRESS: u16 = 0xC000;
+const W_RAM_FIXED_END: u16 = 0xCFFF;
+const W_RAM_SWITCHABLE_ADDRESS: u16 = 0xD000;
 const OAM_ADDRESS: u16 = 0xFE00;
 const IO_ADDRESS: u16 = 0xFF00;
 const H_RAM_ADDR: u16 = 0xFF80;
 const BG_PAL_ADDR: u16 = 0xFF47;
+const VBK_ADDRESS: u16 = 0xFF4F;
+const KEY1_ADDRESS: u16 = 0xFF4D;
+const SVBK_ADDRESS: u16 = 0xFF70;
+const JOYPAD_ADDRESS: u16 = 0xFF00;
 pub const INTERRUPT_ENABLE_ADDRESS: u16 = 0xFFFF;
 pub const INTERRUPT_FLAGS_ADDRESS: u16 = 0xFF0F;
+const TIMER_INTERRUPT_BIT: u8 = 0x04;
+const JOYPAD_INTERRUPT_BIT: u8 = 0x10;
 
 pub enum Opcode {
     Regular(u8),
     CB(u8),
 }
 
+// Everything Mmu owns that makes up the live memory map. Cartridge ROM and
+// the Gpu are excluded: ROM is read-only and the Gpu snapshots itself.
+#[derive(Serialize, Deserialize)]
+struct MmuState {
+    mbc: Mbc,
+    timer: Timer,
+    dma: Dma,
+    ext_ram: [[u8; EXT_RAM_SIZE]; EXT_RAM_BANK_COUNT],
+    w_ram: [[u8; W_RAM_BANK_SIZE]; W_RAM_BANK_COUNT],
+    svbk: u8,
+    echo_ram: [u8; ECHO_RAM_SIZE],
+    h_ram: [u8; H_RAM_SIZE],
+    io: [u8; IO_SIZE],
+    interrupts_enabled: u8,
+    interrupt_flags: u8,
+    is_booted: bool,
+    is_cgb: bool,
+    key1: u8,
+    double_speed: bool,
+    joypad: Joypad,
+}
+
 pub struct Mmu<'a> {
     cartridge: &'a Cartridge,
     pub gpu: &'a mut Gpu<'a>,
     bios: Option<&'a Cartridge>,
-    ext_ram: [u8; EXT_RAM_SIZE],
-    w_ram: [u8; W_RAM_SIZE],
+    mbc: Mbc,
+    battery: BatterySave,
+    timer: Timer,
+    dma: Dma,
+    // Banks actually backed by the cartridge's declared RAM size (header
+    // byte 0x0149); flush_save only persists this many.
+    ram_bank_count: usize,
+    ext_ram: [[u8; EXT_RAM_SIZE]; EXT_RAM_BANK_COUNT],
+    // Bank 0 is the fixed 0xC000-0xCFFF window; banks 1-7 are switched into
+    // 0xD000-0xDFFF by SVBK on CGB hardware.
+    w_ram: [[u8; W_RAM_BANK_SIZE]; W_RAM_BANK_COUNT],
+    svbk: u8,
     echo_ram: [u8; ECHO_RAM_SIZE],
     h_ram: [u8; H_RAM_SIZE],
     //Remove this when io handling is implemented
@@ -39,7 +93,10 @@ pub struct Mmu<'a> {
     interrupts_enabled: u8,
     interrupt_flags: u8,
     is_booted: bool,
-    keypad: u8,
+    is_cgb: bool,
+    key1: u8,
+    double_speed: bool,
+    joypad: Joypad,
 }
 
 impl<'a> Mmu<'a> {
@@ -47,13 +104,36 @@ impl<'a> Mmu<'a> {
         cartridge: &'a Cartridge,
         gpu: &'a mut Gpu<'a>,
         bios: Option<&'a Cartridge>,
+        save_path: Option<PathBuf>,
     ) -> Mmu<'a> {
+        let header_byte = cartridge.read(CARTRIDGE_TYPE_ADDRESS);
+        let mbc_type = MbcType::from_header_byte(header_byte);
+        let battery = BatterySave::new(save_path.filter(|_| has_battery(header_byte)));
+        let is_cgb = cartridge.read(CGB_FLAG_ADDRESS) & 0x80 != 0;
+        let ram_bank_count = ram_bank_count(cartridge.read(RAM_SIZE_ADDRESS));
+
+        let mut ext_ram = [[0; EXT_RAM_SIZE]; EXT_RAM_BANK_COUNT];
+        if let Some(saved) = battery.load() {
+            for (bank, chunk) in saved.chunks(EXT_RAM_SIZE).enumerate() {
+                if bank >= ram_bank_count {
+                    break;
+                }
+                ext_ram[bank][..chunk.len()].copy_from_slice(chunk);
+            }
+        }
+
         Mmu {
             cartridge,
             gpu,
             bios,
-            ext_ram: [0; EXT_RAM_SIZE],
-            w_ram: [0; W_RAM_SIZE],
+            mbc: Mbc::new(mbc_type),
+            battery,
+            timer: Timer::new(),
+            dma: Dma::new(),
+            ram_bank_count,
+            ext_ram,
+            w_ram: [[0; W_RAM_BANK_SIZE]; W_RAM_BANK_COUNT],
+            svbk: 0,
             echo_ram: [0; ECHO_RAM_SIZE],
             h_ram: [0; H_RAM_SIZE],
             //Remove this when io handling is implemented
@@ -61,22 +141,43 @@ impl<'a> Mmu<'a> {
             interrupts_enabled: 0,
             interrupt_flags: 0,
             is_booted: false,
-            keypad: 0xFF,
+            is_cgb,
+            key1: 0,
+            double_speed: false,
+            joypad: Joypad::new(),
+        }
+    }
+
+    // SVBK selects banks 1-7; 0 (and non-CGB hardware, which never writes
+    // SVBK) both mean bank 1.
+    fn w_ram_bank(&self) -> usize {
+        match self.svbk & 0x07 {
+            0 => 1,
+            bank => bank as usize,
         }
     }
 
     pub fn write(&mut self, address: u16, value: u8) {
         match address {
 
-
+            0..=0x7FFF => self.mbc.write_register(address, value),
             0xFF50 => self.is_booted = true,
             INTERRUPT_FLAGS_ADDRESS => self.interrupt_flags = value,
             VRAM_ADDRESS..=0x9FFF => self.gpu.write_vram(address, value),
-            EXT_RAM_ADDRESS..=0xBFFF => self.ext_ram[(address - EXT_RAM_ADDRESS) as usize] = value,
-            W_RAM_ADDRESS..=0xDFFF => {
-                self.w_ram[(address - W_RAM_ADDRESS) as usize] = value
+            EXT_RAM_ADDRESS..=0xBFFF => {
+                if self.mbc.ram_enabled() {
+                    let bank = self.mbc.ram_bank() as usize % EXT_RAM_BANK_COUNT;
+                    self.ext_ram[bank][(address - EXT_RAM_ADDRESS) as usize] = value;
+                    self.battery.mark_dirty();
+                }
+            }
+            W_RAM_ADDRESS..=W_RAM_FIXED_END => {
+                self.w_ram[0][(address - W_RAM_ADDRESS) as usize] = value
+            }
+            W_RAM_SWITCHABLE_ADDRESS..=0xDFFF => {
+                let bank = self.w_ram_bank();
+                self.w_ram[bank][(address - W_RAM_SWITCHABLE_ADDRESS) as usize] = value
             }
-            ,
             //TODO: What is 0xFDFE??
             ECHO_RAM_ADDRESS..=0xFDFE => {
                 self.echo_ram[(address - ECHO_RAM_ADDRESS) as usize] = value
@@ -84,11 +185,11 @@ impl<'a> Mmu<'a> {
             //TODO: What is 0xFE9E??
             //TODO: Do GPU stuff here
             OAM_ADDRESS..=0xFE9E => self.gpu.write_oam(address, value),
+            DIV_ADDRESS..=TAC_ADDRESS => self.timer.write(address, value),
             //TODO: What is 0xFF7E
             IO_ADDRESS..=0xFF7E => {
-                if address == 0xFF00 {
-                    //Implement Keypad
-                    self.keypad = value;
+                if address == JOYPAD_ADDRESS {
+                    self.joypad.write(value);
                 }
 
                 if address == 0xFF40 {
@@ -108,7 +209,19 @@ impl<'a> Mmu<'a> {
                 }
 
                 if address == 0xFF46 {
-                    self.dma_transfer(value);
+                    self.dma.start(value);
+                }
+
+                if address == VBK_ADDRESS && self.is_cgb {
+                    self.gpu.set_vram_bank(value & 0x01);
+                }
+
+                if address == SVBK_ADDRESS && self.is_cgb {
+                    self.svbk = value;
+                }
+
+                if address == KEY1_ADDRESS && self.is_cgb {
+                    self.key1 = (self.key1 & 0x80) | (value & 0x01);
                 }
 
                 self.io[(address - IO_ADDRESS) as usize] = value;
@@ -119,22 +232,6 @@ impl<'a> Mmu<'a> {
         };
     }
 
-    fn dma_transfer(&mut self, source_address: u8) {
-        //DMA Transfer starts to OAM
-        //Start address = value * 0x100 (value << 8)
-        //Destination = OAM
-        //Write everything from start for OAM length
-        //OAM Length = 0xA0 (160)
-        let start_address: u16 = (source_address as u16) << 8;
-
-        for offset in 0..160 {
-            self.gpu
-                .write_oam(OAM_ADDRESS + offset, self.read(start_address + offset))
-        }
-        //TODO: Cycles are missing here
-        //The transfer takes 160 machine cycles
-    }
-
     pub fn write_word(&mut self, address: u16, value: u16) {
         self.write(address, (value >> 8) as u8);
         self.write(address + 0x01, value as u8);
@@ -155,22 +252,37 @@ impl<'a> Mmu<'a> {
                     self.cartridge.read(address)
                 }
             }
-            USER_PROGRAM_AREA_ADDRESS..=0x7FFF => self.cartridge.read(address),
+            USER_PROGRAM_AREA_ADDRESS..=ROM_BANK_0_END => self.cartridge.read(address),
+            // Bank-switched ROM: index into whichever 16 KiB window the MBC
+            // currently has switched into 0x4000-0x7FFF.
+            ROM_BANK_N_ADDRESS..=0x7FFF => self
+                .cartridge
+                .read_rom_banked(self.mbc.rom_bank(), address - ROM_BANK_N_ADDRESS),
             VRAM_ADDRESS..=0x9FFF => self.gpu.read_vram(address),
-            EXT_RAM_ADDRESS..=0xBFFF => self.ext_ram[(address - EXT_RAM_ADDRESS) as usize],
-            W_RAM_ADDRESS..=0xDFFF => self.w_ram[(address - W_RAM_ADDRESS) as usize],
+            EXT_RAM_ADDRESS..=0xBFFF => {
+                if self.mbc.ram_enabled() {
+                    let bank = self.mbc.ram_bank() as usize % EXT_RAM_BANK_COUNT;
+                    self.ext_ram[bank][(address - EXT_RAM_ADDRESS) as usize]
+                } else {
+                    0xFF
+                }
+            }
+            W_RAM_ADDRESS..=W_RAM_FIXED_END => self.w_ram[0][(address - W_RAM_ADDRESS) as usize],
+            W_RAM_SWITCHABLE_ADDRESS..=0xDFFF => {
+                self.w_ram[self.w_ram_bank()][(address - W_RAM_SWITCHABLE_ADDRESS) as usize]
+            }
             //TODO: What is 0xFDFE??
             ECHO_RAM_ADDRESS..=0xFDFE => self.echo_ram[(address - ECHO_RAM_ADDRESS) as usize],
             //TODO: What is 0xFE9E??
             //TODO: Do GPU stuff here
             OAM_ADDRESS..=0xFE9E => self.gpu.read_oam(address),
+            DIV_ADDRESS..=TAC_ADDRESS => self.timer.read(address),
             //TODO: What is 0xFF7F
             //Unusable memory. Return 0
             0xFEA0..=0xFEFE => 0,
             IO_ADDRESS..=0xFF7E => {
-                if address == 0xFF00 {
-                    //Implement Keypad
-                    return 0xFF;
+                if address == JOYPAD_ADDRESS {
+                    return self.joypad.read();
                 }
 
                 if address == 0xFF40 {
@@ -189,6 +301,11 @@ impl<'a> Mmu<'a> {
                     return self.gpu.current_scanline;
                 }
 
+                if address == KEY1_ADDRESS {
+                    let speed_bit = if self.double_speed { 0x80 } else { 0x00 };
+                    return speed_bit | (self.key1 & 0x01);
+                }
+
                 self.io[(address - IO_ADDRESS) as usize]
             }
             H_RAM_ADDR..=0xFFFD => self.h_ram[(address - H_RAM_ADDR) as usize],
@@ -209,4 +326,110 @@ impl<'a> Mmu<'a> {
             _ => Opcode::Regular(op_code),
         }
     }
+
+    // Advances hardware clocked off the CPU's step count, currently just
+    // the timer. Called once per CPU step with the number of T-cycles spent.
+    pub fn step(&mut self, cycles: u8) {
+        if self.timer.step(cycles) {
+            self.interrupt_flags |= TIMER_INTERRUPT_BIT;
+        }
+    }
+
+    // Copies one OAM DMA byte if a transfer is in progress. Called once per
+    // machine cycle.
+    pub fn dma_tick(&mut self) {
+        if let Some((source, offset)) = self.dma.tick() {
+            let byte = self.read(source);
+            self.gpu.write_oam(OAM_ADDRESS + offset, byte);
+        }
+    }
+
+    pub fn is_dma_active(&self) -> bool {
+        self.dma.is_active()
+    }
+
+    // Called by the CPU when executing STOP: if KEY1 is armed, flips the
+    // double-speed bit and clears the arming bit.
+    pub fn try_speed_switch(&mut self) {
+        if self.key1 & 0x01 != 0 {
+            self.double_speed = !self.double_speed;
+            self.key1 &= !0x01;
+        }
+    }
+
+    pub fn is_double_speed(&self) -> bool {
+        self.double_speed
+    }
+
+    // Frontend entry point for updating a button's pressed state. Raises
+    // the joypad interrupt on a high-to-low transition.
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        if self.joypad.set_button(button, pressed) {
+            self.interrupt_flags |= JOYPAD_INTERRUPT_BIT;
+        }
+    }
+
+    // Flushes battery-backed external RAM to disk if it has changed since
+    // the last flush. Call this periodically and on shutdown.
+    pub fn flush_save(&mut self) -> std::io::Result<()> {
+        if !self.battery.is_dirty() {
+            return Ok(());
+        }
+
+        let flat: Vec<u8> = self.ext_ram[..self.ram_bank_count]
+            .iter()
+            .flatten()
+            .copied()
+            .collect();
+        self.battery.flush(&flat)
+    }
+
+    // Serializes the full memory map to a byte blob a frontend can stash in
+    // a save-state slot.
+    pub fn save_state(&self) -> bincode::Result<Vec<u8>> {
+        let state = MmuState {
+            mbc: self.mbc.clone(),
+            timer: self.timer.clone(),
+            dma: self.dma.clone(),
+            ext_ram: self.ext_ram,
+            w_ram: self.w_ram,
+            svbk: self.svbk,
+            echo_ram: self.echo_ram,
+            h_ram: self.h_ram,
+            io: self.io,
+            interrupts_enabled: self.interrupts_enabled,
+            interrupt_flags: self.interrupt_flags,
+            is_booted: self.is_booted,
+            is_cgb: self.is_cgb,
+            key1: self.key1,
+            double_speed: self.double_speed,
+            joypad: self.joypad.clone(),
+        };
+
+        bincode::serialize(&state)
+    }
+
+    // Restores the memory map from a blob produced by `save_state`.
+    pub fn load_state(&mut self, blob: &[u8]) -> bincode::Result<()> {
+        let state: MmuState = bincode::deserialize(blob)?;
+
+        self.mbc = state.mbc;
+        self.timer = state.timer;
+        self.dma = state.dma;
+        self.ext_ram = state.ext_ram;
+        self.w_ram = state.w_ram;
+        self.svbk = state.svbk;
+        self.echo_ram = state.echo_ram;
+        self.h_ram = state.h_ram;
+        self.io = state.io;
+        self.interrupts_enabled = state.interrupts_enabled;
+        self.interrupt_flags = state.interrupt_flags;
+        self.is_booted = state.is_booted;
+        self.is_cgb = state.is_cgb;
+        self.key1 = state.key1;
+        self.double_speed = state.double_speed;
+        self.joypad = state.joypad;
+
+        Ok(())
+    }
 }
\ No newline at end of file
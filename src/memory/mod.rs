@@ -0,0 +1,6 @@
+mod dma;
+pub mod joypad;
+mod mbc;
+pub mod mmu;
+mod save;
+mod timer;
@@ -0,0 +1,42 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+// Persists battery-backed external RAM to a `.sav` file next to the ROM.
+pub struct BatterySave {
+    path: Option<PathBuf>,
+    dirty: bool,
+}
+
+impl BatterySave {
+    pub fn new(path: Option<PathBuf>) -> BatterySave {
+        BatterySave { path, dirty: false }
+    }
+
+    pub fn load(&self) -> Option<Vec<u8>> {
+        self.path.as_ref().and_then(|path| fs::read(path).ok())
+    }
+
+    pub fn mark_dirty(&mut self) {
+        if self.path.is_some() {
+            self.dirty = true;
+        }
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn flush(&mut self, ext_ram: &[u8]) -> io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        if let Some(path) = &self.path {
+            fs::write(path, ext_ram)?;
+            self.dirty = false;
+        }
+
+        Ok(())
+    }
+}
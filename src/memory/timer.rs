@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+
+pub const DIV_ADDRESS: u16 = 0xFF04;
+pub const TIMA_ADDRESS: u16 = 0xFF05;
+pub const TMA_ADDRESS: u16 = 0xFF06;
+pub const TAC_ADDRESS: u16 = 0xFF07;
+
+const DIV_PERIOD: u32 = 256;
+
+// T-cycles per TIMA tick for each TAC frequency selection (4096/262144/65536/16384 Hz).
+const TIMA_PERIODS: [u32; 4] = [1024, 16, 64, 256];
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Timer {
+    div: u8,
+    div_counter: u32,
+    tima: u8,
+    tima_counter: u32,
+    tma: u8,
+    tac: u8,
+}
+
+impl Timer {
+    pub fn new() -> Timer {
+        Timer {
+            div: 0,
+            div_counter: 0,
+            tima: 0,
+            tima_counter: 0,
+            tma: 0,
+            tac: 0,
+        }
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            DIV_ADDRESS => self.div,
+            TIMA_ADDRESS => self.tima,
+            TMA_ADDRESS => self.tma,
+            TAC_ADDRESS => self.tac,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            DIV_ADDRESS => {
+                self.div = 0;
+                self.div_counter = 0;
+            }
+            TIMA_ADDRESS => self.tima = value,
+            TMA_ADDRESS => self.tma = value,
+            TAC_ADDRESS => self.tac = value,
+            _ => {}
+        }
+    }
+
+    // Advances the timer by `cycles` T-cycles. Returns true on a TIMA
+    // overflow, so the caller can raise the timer interrupt.
+    pub fn step(&mut self, cycles: u8) -> bool {
+        self.div_counter += cycles as u32;
+        while self.div_counter >= DIV_PERIOD {
+            self.div_counter -= DIV_PERIOD;
+            self.div = self.div.wrapping_add(1);
+        }
+
+        if self.tac & 0x04 == 0 {
+            return false;
+        }
+
+        let period = TIMA_PERIODS[(self.tac & 0x03) as usize];
+        let mut overflowed = false;
+
+        self.tima_counter += cycles as u32;
+        while self.tima_counter >= period {
+            self.tima_counter -= period;
+            let (result, overflow) = self.tima.overflowing_add(1);
+            self.tima = if overflow { self.tma } else { result };
+            overflowed |= overflow;
+        }
+
+        overflowed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn div_increments_every_256_t_cycles_regardless_of_tac() {
+        let mut timer = Timer::new();
+        timer.step(255);
+        assert_eq!(timer.read(DIV_ADDRESS), 0);
+        timer.step(1);
+        assert_eq!(timer.read(DIV_ADDRESS), 1);
+    }
+
+    #[test]
+    fn writing_div_resets_it_to_zero() {
+        let mut timer = Timer::new();
+        timer.step(256);
+        assert_eq!(timer.read(DIV_ADDRESS), 1);
+
+        timer.write(DIV_ADDRESS, 0xFF);
+        assert_eq!(timer.read(DIV_ADDRESS), 0);
+    }
+
+    #[test]
+    fn tima_does_not_advance_while_disabled_in_tac() {
+        let mut timer = Timer::new();
+        timer.write(TAC_ADDRESS, 0x00); // enable bit (0x04) clear
+        assert!(!timer.step(1024));
+        assert_eq!(timer.read(TIMA_ADDRESS), 0);
+    }
+
+    #[test]
+    fn tima_uses_the_period_selected_by_the_tac_clock_select_bits() {
+        // Clock select 0b01 selects the 16 T-cycle period (262144 Hz).
+        let mut timer = Timer::new();
+        timer.write(TAC_ADDRESS, 0x05);
+        timer.step(15);
+        assert_eq!(timer.read(TIMA_ADDRESS), 0);
+        timer.step(1);
+        assert_eq!(timer.read(TIMA_ADDRESS), 1);
+    }
+
+    #[test]
+    fn tima_overflow_reloads_from_tma_and_reports_the_interrupt() {
+        let mut timer = Timer::new();
+        timer.write(TAC_ADDRESS, 0x05); // enabled, 16 T-cycle period
+        timer.write(TMA_ADDRESS, 0x10);
+        timer.write(TIMA_ADDRESS, 0xFF);
+
+        let overflowed = timer.step(16);
+
+        assert!(overflowed);
+        assert_eq!(timer.read(TIMA_ADDRESS), 0x10);
+    }
+}